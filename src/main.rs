@@ -1,22 +1,69 @@
 use modelica_rust_ffi::{SimpleThermalComponent, SimulationComponent};
 use tokio::time::{interval, Duration};
 use tokio_modbus::prelude::*;
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use serde::Deserialize;
 
+mod mqtt;
+mod registers;
+mod scheduler;
+mod shutdown;
+mod transport;
+
+use registers::{encode, RegisterDef, RegisterType};
+use scheduler::Scheduler;
+use std::time::Instant;
+use tokio::sync::broadcast;
+use transport::{SerialConfig, Transport};
+
 #[derive(Debug, Deserialize, Clone)]
-struct ModbusConfig {
+pub(crate) struct ModbusConfig {
     port: u16,
     update_interval_ms: u64,
-    registers: RegisterMapping,
+    registers: Vec<RegisterDef>,
+    #[serde(default = "ModbusConfig::default_coils")]
+    coils: Vec<CoilDef>,
+    mqtt: Option<MqttConfig>,
+    #[serde(default)]
+    transport: Transport,
+    serial: Option<SerialConfig>,
 }
 
+impl ModbusConfig {
+    fn default_coils() -> Vec<CoilDef> {
+        vec![CoilDef { name: "heater".to_string(), address: 0 }]
+    }
+}
+
+/// A named, writable coil, e.g. the heater on/off control. Gives MQTT (and any
+/// future client) a name -> address lookup instead of hardcoding addresses.
 #[derive(Debug, Deserialize, Clone)]
-struct RegisterMapping {
-    temperature_address: u16,
-    heater_state_address: u16,
+pub(crate) struct CoilDef {
+    pub(crate) name: String,
+    pub(crate) address: u16,
+}
+
+/// Every valid coil address, derived from the coil table the same way
+/// `registers::valid_addresses` is derived from the register map.
+fn valid_coils(coils: &[CoilDef]) -> HashSet<u16> {
+    coils.iter().map(|coil| coil.address).collect()
+}
+
+/// Settings for the optional MQTT bridge; absent when `[mqtt]` is not in the TOML.
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct MqttConfig {
+    pub(crate) broker_host: String,
+    #[serde(default = "MqttConfig::default_broker_port")]
+    pub(crate) broker_port: u16,
+    pub(crate) topic_prefix: String,
+}
+
+impl MqttConfig {
+    fn default_broker_port() -> u16 {
+        1883
+    }
 }
 
 impl Default for ModbusConfig {
@@ -24,22 +71,42 @@ impl Default for ModbusConfig {
         Self {
             port: 5502,
             update_interval_ms: 100,
-            registers: RegisterMapping {
-                temperature_address: 40001,
-                heater_state_address: 40002,
-            },
+            registers: vec![
+                RegisterDef {
+                    name: "temperature".to_string(),
+                    address: 40001,
+                    kind: RegisterType::U16,
+                    scale: 100.0,
+                    swap_words: false,
+                    output: "temperature".to_string(),
+                    period: "1s".to_string(),
+                },
+                RegisterDef {
+                    name: "heater_state".to_string(),
+                    address: 40002,
+                    kind: RegisterType::U16,
+                    scale: 100.0,
+                    swap_words: false,
+                    output: "heaterStatus".to_string(),
+                    period: "0ms".to_string(),
+                },
+            ],
+            coils: ModbusConfig::default_coils(),
+            mqtt: None,
+            transport: Transport::Tcp,
+            serial: None,
         }
     }
 }
 
 /// Shared state between Modbus server and simulation
-struct SharedState {
+pub(crate) struct SharedState {
     /// Holding registers (address -> value)
-    holding_registers: HashMap<u16, u16>,
+    pub(crate) holding_registers: HashMap<u16, u16>,
     /// Input registers (address -> value)
-    input_registers: HashMap<u16, u16>,
+    pub(crate) input_registers: HashMap<u16, u16>,
     /// Coils (address -> value)
-    coils: HashMap<u16, bool>,
+    pub(crate) coils: HashMap<u16, bool>,
 }
 
 impl SharedState {
@@ -52,9 +119,40 @@ impl SharedState {
     }
 }
 
+/// Maximum quantity of registers a single `ReadHoldingRegisters` may request (Modbus spec).
+const MAX_READ_REGISTERS: u16 = 125;
+/// Maximum quantity of coils a single `ReadCoils` may request (Modbus spec).
+const MAX_READ_COILS: u16 = 2000;
+
 /// Custom Modbus service that reads from shared state
-struct ModbusService {
-    state: Arc<Mutex<SharedState>>,
+pub(crate) struct ModbusService {
+    pub(crate) state: Arc<Mutex<SharedState>>,
+    /// Every valid holding-register address, derived from the register map.
+    /// A set rather than a span, so a gap between two non-contiguous
+    /// registers is rejected instead of silently zero-filled.
+    pub(crate) valid_addresses: Arc<HashSet<u16>>,
+    /// Every valid coil address, derived from the config-driven coil table.
+    /// A set rather than the old hardcoded single-address range, so any
+    /// configured coil (not just coil 0) is reachable over Modbus.
+    pub(crate) valid_coils: Arc<HashSet<u16>>,
+    /// Flipped once the shutdown tripwire fires; in-flight requests already
+    /// being serviced complete normally, but any new request on this (or any
+    /// other already-open) connection is refused instead of serviced forever.
+    pub(crate) shutting_down: Arc<AtomicBool>,
+}
+
+/// Checks `addr..addr+count` against `max_count` and `is_valid`, matching the
+/// exception codes a real Modbus device returns for out-of-range or malformed
+/// read requests.
+fn validate_read(addr: u16, count: u16, max_count: u16, is_valid: impl Fn(u16) -> bool) -> Result<(), Exception> {
+    if count == 0 || count > max_count {
+        return Err(Exception::IllegalDataValue);
+    }
+    let last = addr.checked_add(count - 1).ok_or(Exception::IllegalDataAddress)?;
+    if !(addr..=last).all(is_valid) {
+        return Err(Exception::IllegalDataAddress);
+    }
+    Ok(())
 }
 
 impl tokio_modbus::server::Service for ModbusService {
@@ -64,11 +162,18 @@ impl tokio_modbus::server::Service for ModbusService {
     type Future = std::pin::Pin<Box<dyn std::future::Future<Output = Result<Response, Exception>> + Send>>;
 
     fn call(&self, req: Self::Request) -> Self::Future {
+        if self.shutting_down.load(Ordering::Relaxed) {
+            return Box::pin(async { Err(Exception::ServerDeviceBusy) });
+        }
+
         let state = self.state.clone();  // Clone the Arc, not lock yet
-        
+        let valid_addresses = self.valid_addresses.clone();
+        let valid_coils = self.valid_coils.clone();
+
         Box::pin(async move {
             match req {
                 Request::ReadHoldingRegisters(addr, count) => {
+                    validate_read(addr, count, MAX_READ_REGISTERS, |a| valid_addresses.contains(&a))?;
                     let state = state.lock().unwrap();  // Lock once here
                     let mut values = Vec::new();
                     for i in 0..count {
@@ -78,33 +183,25 @@ impl tokio_modbus::server::Service for ModbusService {
                     }
                     Ok(Response::ReadHoldingRegisters(values))
                 }
-                
-                Request::ReadInputRegisters(addr, count) => {
-                    let state = state.lock().unwrap();
-                    let mut values = Vec::new();
-                    for i in 0..count {
-                        let register_addr = addr + i;
-                        let value = state.input_registers.get(&register_addr).copied().unwrap_or(0);
-                        values.push(value);
-                    }
-                    Ok(Response::ReadInputRegisters(values))
+
+                Request::ReadInputRegisters(_addr, _count) => {
+                    // This device declares zero input registers; reject every
+                    // read instead of zero-filling from the never-populated map.
+                    Err(Exception::IllegalDataAddress)
                 }
-                
-                Request::WriteSingleRegister(addr, value) => {
-                    let mut state = state.lock().unwrap();  // Mutable lock
-                    state.holding_registers.insert(addr, value);
-                    Ok(Response::WriteSingleRegister(addr, value))
+
+                Request::WriteSingleRegister(_addr, _value) => {
+                    // Every declared holding register mirrors a simulation output and is
+                    // read-only; addresses outside the map don't exist either way.
+                    Err(Exception::IllegalDataAddress)
                 }
-                
-                Request::WriteMultipleRegisters(addr, values) => {
-                    let mut state = state.lock().unwrap();
-                    for (i, value) in values.iter().enumerate() {
-                        state.holding_registers.insert(addr + i as u16, *value);
-                    }
-                    Ok(Response::WriteMultipleRegisters(addr, values.len() as u16))
+
+                Request::WriteMultipleRegisters(_addr, _values) => {
+                    Err(Exception::IllegalDataAddress)
                 }
-                
+
                 Request::ReadCoils(addr, count) => {
+                    validate_read(addr, count, MAX_READ_COILS, |a| valid_coils.contains(&a))?;
                     let state = state.lock().unwrap();
                     let mut values = Vec::new();
                     for i in 0..count {
@@ -114,13 +211,16 @@ impl tokio_modbus::server::Service for ModbusService {
                     }
                     Ok(Response::ReadCoils(values))
                 }
-                
+
                 Request::WriteSingleCoil(addr, value) => {
+                    if !valid_coils.contains(&addr) {
+                        return Err(Exception::IllegalDataAddress);
+                    }
                     let mut state = state.lock().unwrap();
                     state.coils.insert(addr, value);
                     Ok(Response::WriteSingleCoil(addr, value))
                 }
-                
+
                 _ => Err(Exception::IllegalFunction),
             }
         })
@@ -147,6 +247,7 @@ fn load_config() -> ModbusConfig {
 async fn simulation_loop(
     state: Arc<Mutex<SharedState>>,
     config: ModbusConfig,
+    mut shutdown: broadcast::Receiver<()>,
 ) {
     println!("Starting simulation loop...");
     
@@ -161,50 +262,62 @@ async fn simulation_loop(
     
     let mut ticker = interval(Duration::from_millis(config.update_interval_ms));
     let dt = config.update_interval_ms as f64 / 1000.0; // Convert to seconds
-    
+
+    // Each register refreshes on its own period; physics keep stepping every tick
+    let periods = config.registers.iter().map(|def| scheduler::parse_period(&def.period)).collect();
+    let mut scheduler = Scheduler::new(periods);
+
     loop {
-        ticker.tick().await;
-        
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.recv() => {
+                println!("Simulation loop shutting down, flushing final register snapshot...");
+                let _ = component.step(dt);
+                let mut state = state.lock().unwrap();
+                for def in &config.registers {
+                    if let Ok(value) = component.get_output(&def.output) {
+                        for (addr, word) in encode(def, value) {
+                            state.holding_registers.insert(addr, word);
+                        }
+                    }
+                }
+                return;
+            }
+        }
+
         // Check if heater should be on (read from coil or register)
         let heater_on = {
             let state = state.lock().unwrap();
             // Check coil 0 for heater control
             state.coils.get(&0).copied().unwrap_or(false)
         };
-        
+
         // Update simulation input
         component.set_bool_input("heaterOn", heater_on)
             .expect("Failed to set heater input");
-        
+
         // Step simulation
         component.step(dt)
             .expect("Failed to step simulation");
-        
-        // Read outputs
+
+        // Read outputs used for the console log below
         let temperature = component.get_output("temperature")
             .expect("Failed to get temperature");
-        let heater_status = component.get_output("heaterStatus")
-            .expect("Failed to get heater status");
-        
-        // Update Modbus registers
-        {
+
+        // Update Modbus registers for whichever signals are due this tick
+        let due = scheduler.due(Instant::now());
+        if !due.is_empty() {
             let mut state = state.lock().unwrap();
-            
-            // Temperature scaled by 100 (e.g., 273.15 K -> 27315)
-            let temp_scaled = (temperature * 100.0) as u16;
-            state.holding_registers.insert(
-                config.registers.temperature_address,
-                temp_scaled
-            );
-            
-            // Heater state (0 or 100)
-            let heater_scaled = (heater_status * 100.0) as u16;
-            state.holding_registers.insert(
-                config.registers.heater_state_address,
-                heater_scaled
-            );
+            for i in due {
+                let def = &config.registers[i];
+                let value = component.get_output(&def.output)
+                    .unwrap_or_else(|_| panic!("Failed to get output \"{}\"", def.output));
+                for (addr, word) in encode(def, value) {
+                    state.holding_registers.insert(addr, word);
+                }
+            }
         }
-        
+
         // Log every 10 seconds
         if ticker.period().as_secs() % 10 == 0 {
             println!(
@@ -218,66 +331,172 @@ async fn simulation_loop(
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("SimpleThermalMVP Modbus TCP Server");
+    println!("SimpleThermalMVP Modbus Server");
     println!("===================================");
-    
+
     // Load configuration
     let config = load_config();
     println!("\nConfiguration:");
+    println!("  Transport: {:?}", config.transport);
     println!("  Port: {}", config.port);
     println!("  Update interval: {} ms", config.update_interval_ms);
-    println!("  Temperature register: {}", config.registers.temperature_address);
-    println!("  Heater state register: {}", config.registers.heater_state_address);
+    for def in &config.registers {
+        println!("  Register {} ({:?}): {}", def.address, def.kind, def.name);
+    }
     println!("  Heater control coil: 0");
-    
+
     // Create shared state
     let state = Arc::new(Mutex::new(SharedState::new()));
-    
+
     // Initialize registers to zero
     {
         let mut s = state.lock().unwrap();
-        s.holding_registers.insert(config.registers.temperature_address, 25000); // 250.0 K
-        s.holding_registers.insert(config.registers.heater_state_address, 0);
+        for def in &config.registers {
+            for addr in def.address_range() {
+                s.holding_registers.insert(addr, 0);
+            }
+        }
         s.coils.insert(0, false);
     }
     
+    // Tripwire fired once on SIGINT/SIGTERM so every subsystem can wind down cleanly
+    let (tripwire, _) = broadcast::channel::<()>(1);
+    shutdown::spawn_signal_listener(tripwire.clone());
+
+    // Flipped once the tripwire fires; shared with every ModbusService so an
+    // already-open connection stops accepting new requests, not just the accept loop.
+    let shutting_down = Arc::new(AtomicBool::new(false));
+    {
+        let shutting_down = shutting_down.clone();
+        let mut shutdown = tripwire.subscribe();
+        tokio::spawn(async move {
+            let _ = shutdown.recv().await;
+            shutting_down.store(true, Ordering::Relaxed);
+        });
+    }
+
+    // Background tasks whose in-flight work (final register snapshot, stopped
+    // status, draining connections) must finish before the process exits.
+    let mut background_tasks = Vec::new();
+
     // Start simulation loop in background
     let sim_state = state.clone();
     let sim_config = config.clone();
-    tokio::spawn(async move {
-        simulation_loop(sim_state, sim_config).await;
-    });
-    
+    let sim_shutdown = tripwire.subscribe();
+    background_tasks.push(tokio::spawn(async move {
+        simulation_loop(sim_state, sim_config, sim_shutdown).await;
+    }));
+
+    // Start the optional MQTT bridge so the simulation can be observed/controlled over MQTT
+    if let Some(mqtt_config) = config.mqtt.clone() {
+        println!("\nMQTT bridge: {}:{} (prefix \"{}\")", mqtt_config.broker_host, mqtt_config.broker_port, mqtt_config.topic_prefix);
+        background_tasks.extend(
+            mqtt::spawn_bridge(mqtt_config, config.clone(), state.clone(), tripwire.subscribe()).await,
+        );
+    }
+
     // Start Modbus server
-    let socket_addr: SocketAddr = format!("0.0.0.0:{}", config.port).parse()?;
-    println!("\nStarting Modbus TCP server on {}", socket_addr);
     println!("\nRegister Mapping:");
-    println!("  Register {}: Temperature (K × 100)", config.registers.temperature_address);
-    println!("  Register {}: Heater State (0=OFF, 100=ON)", config.registers.heater_state_address);
+    for def in &config.registers {
+        println!("  Register {}: {} (x{})", def.address, def.name, def.scale);
+    }
     println!("  Coil 0: Heater Control (write TRUE=ON, FALSE=OFF)");
     println!("\nTesting:");
     println!("  cargo test --test modbus_client_test -- --nocapture");
     println!("  cargo run --example simple_client");
     println!("\nServer running. Press Ctrl+C to stop.\n");
-    let listener = tokio::net::TcpListener::bind(socket_addr).await?;
 
-    let server = tokio_modbus::server::tcp::Server::new(listener);
-    
-    let state_clone = state.clone();
-    server
-        .serve(
-            &move |stream, _socket_addr| {  // stream first, then socket_addr
-                let state = state_clone.clone();
-                async move {
-                    let service = ModbusService { state };
-                    Ok(Some((service, stream)))
-                }
-            },
-            &|err| {
-                eprintln!("Modbus connection error: {:?}", err);
-            },
-        )
-        .await?;
-    
-    Ok(())
+    let valid_addresses = registers::valid_addresses(&config.registers);
+    assert!(!valid_addresses.is_empty(), "at least one register must be configured");
+    let valid_addresses = Arc::new(valid_addresses);
+    let valid_coils = Arc::new(valid_coils(&config.coils));
+
+    let result = transport::start_server(
+        &config,
+        state,
+        valid_addresses,
+        valid_coils,
+        tripwire,
+        shutting_down,
+        &mut background_tasks,
+    )
+    .await;
+
+    println!("Waiting for background tasks to finish...");
+    let drain = async {
+        for task in background_tasks {
+            let _ = task.await;
+        }
+    };
+    if tokio::time::timeout(Duration::from_secs(5), drain).await.is_err() {
+        eprintln!("Timed out waiting for background tasks to finish; exiting anyway.");
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(address: u16) -> RegisterDef {
+        RegisterDef {
+            name: "test".to_string(),
+            address,
+            kind: RegisterType::U16,
+            scale: 1.0,
+            swap_words: false,
+            output: "test".to_string(),
+            period: "0ms".to_string(),
+        }
+    }
+
+    fn service(registers: &[RegisterDef], coils: &[CoilDef]) -> ModbusService {
+        ModbusService {
+            state: Arc::new(Mutex::new(SharedState::new())),
+            valid_addresses: Arc::new(registers::valid_addresses(registers)),
+            valid_coils: Arc::new(valid_coils(coils)),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    #[test]
+    fn validate_read_rejects_zero_quantity() {
+        assert_eq!(validate_read(0, 0, MAX_READ_REGISTERS, |_| true), Err(Exception::IllegalDataValue));
+    }
+
+    #[test]
+    fn validate_read_rejects_quantity_over_max() {
+        assert_eq!(
+            validate_read(0, MAX_READ_REGISTERS + 1, MAX_READ_REGISTERS, |_| true),
+            Err(Exception::IllegalDataValue)
+        );
+    }
+
+    #[tokio::test]
+    async fn read_holding_registers_rejects_gap_between_non_contiguous_registers() {
+        let registers = vec![register(10), register(20)];
+        let svc = service(&registers, &[]);
+        let result = svc.call(Request::ReadHoldingRegisters(10, 11)).await;
+        assert_eq!(result, Err(Exception::IllegalDataAddress));
+    }
+
+    #[tokio::test]
+    async fn read_input_registers_always_rejected() {
+        let svc = service(&[register(10)], &[]);
+        let result = svc.call(Request::ReadInputRegisters(0, 1)).await;
+        assert_eq!(result, Err(Exception::IllegalDataAddress));
+    }
+
+    #[tokio::test]
+    async fn write_single_coil_rejects_addresses_outside_the_configured_coil_table() {
+        let coils = vec![CoilDef { name: "heater".to_string(), address: 0 }, CoilDef { name: "fan".to_string(), address: 5 }];
+        let svc = service(&[register(10)], &coils);
+
+        let fan = svc.call(Request::WriteSingleCoil(5, true)).await;
+        assert_eq!(fan, Ok(Response::WriteSingleCoil(5, true)));
+
+        let unconfigured = svc.call(Request::WriteSingleCoil(1, true)).await;
+        assert_eq!(unconfigured, Err(Exception::IllegalDataAddress));
+    }
 }
@@ -0,0 +1,173 @@
+//! Optional Modbus↔MQTT bridge that mirrors `SharedState` onto a broker so the
+//! simulation can be observed and controlled over MQTT as well as raw Modbus.
+
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::interval;
+
+use crate::registers;
+use crate::scheduler::{self, Scheduler};
+use crate::{CoilDef, ModbusConfig, MqttConfig, SharedState};
+use std::time::Instant;
+
+const STATUS_TOPIC: &str = "status";
+
+/// Connects to the broker, arms a LastWill "stopped" status, and spawns the
+/// publish and subscribe tasks that keep MQTT in sync with `state`. `shutdown`
+/// fires once on shutdown, at which point a final "stopped" status is published.
+/// Returns the spawned tasks' handles so the caller can wait for that final
+/// publish to actually complete before the process exits.
+pub(crate) async fn spawn_bridge(
+    mqtt_config: MqttConfig,
+    config: ModbusConfig,
+    state: Arc<Mutex<SharedState>>,
+    shutdown: broadcast::Receiver<()>,
+) -> Vec<tokio::task::JoinHandle<()>> {
+    let status_topic = format!("{}/{}", mqtt_config.topic_prefix, STATUS_TOPIC);
+
+    let mut options = MqttOptions::new(
+        "modelica-modbus-bridge",
+        mqtt_config.broker_host.clone(),
+        mqtt_config.broker_port,
+    );
+    options.set_keep_alive(Duration::from_secs(30));
+    options.set_last_will(LastWill::new(
+        &status_topic,
+        json!({ "status": "stopped" }).to_string(),
+        QoS::AtLeastOnce,
+        true,
+    ));
+
+    let (client, eventloop) = AsyncClient::new(options, 10);
+
+    let set_topic_filter = format!("{}/+/set", mqtt_config.topic_prefix);
+    if let Err(e) = client.subscribe(&set_topic_filter, QoS::AtLeastOnce).await {
+        eprintln!("Failed to subscribe to {}: {:?}", set_topic_filter, e);
+    }
+
+    if let Err(e) = client
+        .publish(&status_topic, QoS::AtLeastOnce, true, json!({ "status": "running" }).to_string())
+        .await
+    {
+        eprintln!("Failed to publish running status: {:?}", e);
+    }
+
+    let coils = config.coils.clone();
+    vec![
+        tokio::spawn(publish_loop(client.clone(), mqtt_config.clone(), config, state.clone(), shutdown.resubscribe())),
+        tokio::spawn(subscribe_loop(eventloop, mqtt_config, coils, state, shutdown.resubscribe())),
+        tokio::spawn(publish_stopped_on_shutdown(client, status_topic, shutdown)),
+    ]
+}
+
+/// Publishes the retained "stopped" status once the tripwire fires, so MQTT
+/// consumers see the same transition a crash's LastWill would produce.
+async fn publish_stopped_on_shutdown(client: AsyncClient, status_topic: String, mut shutdown: broadcast::Receiver<()>) {
+    let _ = shutdown.recv().await;
+    if let Err(e) = client
+        .publish(&status_topic, QoS::AtLeastOnce, true, json!({ "status": "stopped" }).to_string())
+        .await
+    {
+        eprintln!("Failed to publish stopped status: {:?}", e);
+    }
+}
+
+/// On each simulation tick, publishes whichever registers are due under
+/// `<prefix>/<register_name>`, with values scaled back to engineering units.
+/// Reuses each register's own `period` via the same [`Scheduler`] that
+/// `simulation_loop` uses, so a slow signal isn't republished on every tick.
+async fn publish_loop(
+    client: AsyncClient,
+    mqtt_config: MqttConfig,
+    config: ModbusConfig,
+    state: Arc<Mutex<SharedState>>,
+    mut shutdown: broadcast::Receiver<()>,
+) {
+    let mut ticker = interval(Duration::from_millis(config.update_interval_ms));
+    let periods = config.registers.iter().map(|def| scheduler::parse_period(&def.period)).collect();
+    let mut scheduler = Scheduler::new(periods);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = shutdown.recv() => return,
+        }
+
+        for i in scheduler.due(Instant::now()) {
+            let def = &config.registers[i];
+            let words: Vec<u16> = {
+                let state = state.lock().unwrap();
+                def.address_range()
+                    .map(|addr| state.holding_registers.get(&addr).copied().unwrap_or(0))
+                    .collect()
+            };
+            let value = registers::decode(def, &words);
+            publish_value(&client, &mqtt_config, &def.name, value).await;
+        }
+    }
+}
+
+async fn publish_value(client: &AsyncClient, mqtt_config: &MqttConfig, name: &str, value: f64) {
+    let topic = format!("{}/{}", mqtt_config.topic_prefix, name);
+    let payload = json!({ "value": value }).to_string();
+    if let Err(e) = client.publish(&topic, QoS::AtMostOnce, false, payload).await {
+        eprintln!("Failed to publish {}: {:?}", topic, e);
+    }
+}
+
+/// Drives the MQTT event loop, applying `<prefix>/<coil_name>/set` messages to
+/// `state` exactly as `Request::WriteSingleCoil` does for the Modbus side.
+/// `<coil_name>` is resolved against the configured `coils` table, so adding a
+/// coil to the config is enough to make it controllable over MQTT too.
+async fn subscribe_loop(
+    mut eventloop: EventLoop,
+    mqtt_config: MqttConfig,
+    coils: Vec<CoilDef>,
+    state: Arc<Mutex<SharedState>>,
+    mut shutdown: broadcast::Receiver<()>,
+) {
+    let set_prefix = format!("{}/", mqtt_config.topic_prefix);
+    loop {
+        let event = tokio::select! {
+            event = eventloop.poll() => event,
+            _ = shutdown.recv() => break,
+        };
+
+        match event {
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let Some(coil_name) = publish
+                    .topic
+                    .strip_prefix(set_prefix.as_str())
+                    .and_then(|rest| rest.strip_suffix("/set"))
+                else {
+                    continue;
+                };
+
+                match coils.iter().find(|coil| coil.name == coil_name) {
+                    Some(coil) => {
+                        let on = matches!(publish.payload.as_ref(), b"true" | b"1" | b"ON" | b"on");
+                        let mut state = state.lock().unwrap();
+                        state.coils.insert(coil.address, on);
+                    }
+                    None => eprintln!("Ignoring set for unknown coil \"{}\"", coil_name),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("MQTT connection error: {:?}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+
+    // Nothing reaches the broker once this event loop is dropped, so keep it
+    // running until the "stopped" status queued by `publish_stopped_on_shutdown`
+    // is acknowledged (or the connection fails, or a second passes).
+    let _ = tokio::time::timeout(Duration::from_secs(1), async {
+        while !matches!(eventloop.poll().await, Ok(Event::Incoming(Packet::PubAck(_))) | Err(_)) {}
+    })
+    .await;
+}
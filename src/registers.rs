@@ -0,0 +1,216 @@
+//! Config-driven register map: each entry binds a simulation output to one or
+//! more Modbus holding registers, with a numeric type, scale factor, and an
+//! optional word swap for 32-bit values.
+
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+/// Modbus representation of a simulation output.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RegisterType {
+    U16,
+    S16,
+    U32,
+    S32,
+    F32,
+}
+
+impl RegisterType {
+    /// Number of consecutive 16-bit registers this type occupies.
+    pub(crate) fn word_count(self) -> u16 {
+        match self {
+            RegisterType::U16 | RegisterType::S16 => 1,
+            RegisterType::U32 | RegisterType::S32 | RegisterType::F32 => 2,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct RegisterDef {
+    /// Name used for MQTT topics and logging.
+    pub(crate) name: String,
+    /// Address of the first (or only) register.
+    pub(crate) address: u16,
+    #[serde(rename = "type")]
+    pub(crate) kind: RegisterType,
+    /// Multiplied into the simulation value before encoding.
+    #[serde(default = "RegisterDef::default_scale")]
+    pub(crate) scale: f64,
+    /// Swaps the high/low words of 32-bit types; ignored for 16-bit types.
+    #[serde(default)]
+    pub(crate) swap_words: bool,
+    /// Name of the simulation output this register is bound to.
+    pub(crate) output: String,
+    /// How often this register is refreshed, e.g. `"1s"`, `"3s"`, `"1m"`.
+    /// `"0ms"` (the default) refreshes on every base tick.
+    #[serde(default = "RegisterDef::default_period")]
+    pub(crate) period: String,
+}
+
+impl RegisterDef {
+    fn default_scale() -> f64 {
+        1.0
+    }
+
+    fn default_period() -> String {
+        "0ms".to_string()
+    }
+
+    /// The inclusive range of Modbus addresses this register occupies,
+    /// saturating instead of overflowing for a register declared near the
+    /// top of the address space.
+    pub(crate) fn address_range(&self) -> RangeInclusive<u16> {
+        self.address..=self.address.saturating_add(self.kind.word_count() - 1)
+    }
+}
+
+/// Scales `value` and encodes it into `(address, word)` pairs in the order
+/// they must be written, honoring `swap_words` for 32-bit types. Reading the
+/// same addresses back with `ReadHoldingRegisters` reassembles the value
+/// symmetrically since the words are stored exactly as written.
+pub(crate) fn encode(def: &RegisterDef, value: f64) -> Vec<(u16, u16)> {
+    let scaled = value * def.scale;
+    match def.kind {
+        RegisterType::U16 => vec![(def.address, scaled.round() as u16)],
+        RegisterType::S16 => vec![(def.address, scaled.round() as i16 as u16)],
+        RegisterType::U32 => words(def, (scaled.round() as u32).to_be_bytes()),
+        RegisterType::S32 => words(def, (scaled.round() as i32 as u32).to_be_bytes()),
+        RegisterType::F32 => words(def, (scaled as f32).to_bits().to_be_bytes()),
+    }
+}
+
+fn words(def: &RegisterDef, be_bytes: [u8; 4]) -> Vec<(u16, u16)> {
+    let hi = u16::from_be_bytes([be_bytes[0], be_bytes[1]]);
+    let lo = u16::from_be_bytes([be_bytes[2], be_bytes[3]]);
+    let second = def.address.saturating_add(1);
+    if def.swap_words {
+        vec![(def.address, lo), (second, hi)]
+    } else {
+        vec![(def.address, hi), (second, lo)]
+    }
+}
+
+/// Inverse of [`encode`]: reassembles `def.kind.word_count()` register words
+/// (in the order they appear starting at `def.address`) back into an
+/// engineering-unit value. Used by consumers that mirror the register map,
+/// such as the MQTT bridge.
+pub(crate) fn decode(def: &RegisterDef, words: &[u16]) -> f64 {
+    let raw = match def.kind {
+        RegisterType::U16 => words[0] as f64,
+        RegisterType::S16 => words[0] as i16 as f64,
+        RegisterType::U32 => u32_from_words(def, words) as f64,
+        RegisterType::S32 => u32_from_words(def, words) as i32 as f64,
+        RegisterType::F32 => f32::from_bits(u32_from_words(def, words)) as f64,
+    };
+    raw / def.scale
+}
+
+fn u32_from_words(def: &RegisterDef, words: &[u16]) -> u32 {
+    let (hi, lo) = if def.swap_words {
+        (words[1], words[0])
+    } else {
+        (words[0], words[1])
+    };
+    ((hi as u32) << 16) | lo as u32
+}
+
+/// Every holding-register address declared by `defs`, i.e. the device's valid
+/// addresses for reads and (read-only) writes. Built from each register's own
+/// span rather than one combined `min..=max`, so a read landing in a gap
+/// between two non-contiguous registers is rejected instead of silently
+/// zero-filled.
+pub(crate) fn valid_addresses(defs: &[RegisterDef]) -> HashSet<u16> {
+    defs.iter().flat_map(RegisterDef::address_range).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn def(address: u16, kind: RegisterType, scale: f64, swap_words: bool) -> RegisterDef {
+        RegisterDef {
+            name: "test".to_string(),
+            address,
+            kind,
+            scale,
+            swap_words,
+            output: "test".to_string(),
+            period: "0ms".to_string(),
+        }
+    }
+
+    fn roundtrip(d: &RegisterDef, value: f64) -> f64 {
+        let words: Vec<u16> = encode(d, value).into_iter().map(|(_, w)| w).collect();
+        decode(d, &words)
+    }
+
+    #[test]
+    fn u16_roundtrips() {
+        let d = def(100, RegisterType::U16, 100.0, false);
+        assert_eq!(roundtrip(&d, 12.34), 12.34);
+    }
+
+    #[test]
+    fn s16_roundtrips_negative() {
+        let d = def(100, RegisterType::S16, 10.0, false);
+        assert_eq!(roundtrip(&d, -5.0), -5.0);
+    }
+
+    #[test]
+    fn u32_roundtrips() {
+        let d = def(100, RegisterType::U32, 1.0, false);
+        assert_eq!(roundtrip(&d, 123456.0), 123456.0);
+    }
+
+    #[test]
+    fn s32_roundtrips_negative() {
+        let d = def(100, RegisterType::S32, 1.0, false);
+        assert_eq!(roundtrip(&d, -123456.0), -123456.0);
+    }
+
+    #[test]
+    fn f32_roundtrips() {
+        let d = def(100, RegisterType::F32, 1.0, false);
+        assert_eq!(roundtrip(&d, 3.5), 3.5);
+    }
+
+    #[test]
+    fn encode_addresses_are_contiguous() {
+        let d = def(100, RegisterType::U32, 1.0, false);
+        let pairs = encode(&d, 1.0);
+        assert_eq!(pairs[0].0, 100);
+        assert_eq!(pairs[1].0, 101);
+    }
+
+    #[test]
+    fn swap_words_reverses_hi_lo_order_but_still_roundtrips() {
+        let normal = def(100, RegisterType::U32, 1.0, false);
+        let swapped = def(100, RegisterType::U32, 1.0, true);
+        let normal_words: Vec<u16> = encode(&normal, 70000.0).into_iter().map(|(_, w)| w).collect();
+        let swapped_words: Vec<u16> = encode(&swapped, 70000.0).into_iter().map(|(_, w)| w).collect();
+        assert_eq!(normal_words, vec![swapped_words[1], swapped_words[0]]);
+        assert_eq!(roundtrip(&swapped, 70000.0), 70000.0);
+    }
+
+    #[test]
+    fn valid_addresses_covers_every_word_of_every_register() {
+        let defs = vec![def(10, RegisterType::U16, 1.0, false), def(20, RegisterType::U32, 1.0, false)];
+        let addrs = valid_addresses(&defs);
+        assert_eq!(addrs, HashSet::from([10, 20, 21]));
+    }
+
+    #[test]
+    fn valid_addresses_excludes_gaps_between_non_contiguous_registers() {
+        let defs = vec![def(10, RegisterType::U16, 1.0, false), def(20, RegisterType::U16, 1.0, false)];
+        let addrs = valid_addresses(&defs);
+        assert!(!addrs.contains(&15));
+    }
+
+    #[test]
+    fn valid_addresses_saturates_instead_of_overflowing() {
+        let defs = vec![def(u16::MAX, RegisterType::U32, 1.0, false)];
+        assert_eq!(valid_addresses(&defs), HashSet::from([u16::MAX]));
+    }
+}
@@ -0,0 +1,99 @@
+//! Per-signal publish scheduling: lets each register refresh on its own
+//! period while the physics keep stepping at the fast base interval.
+
+use std::time::{Duration, Instant};
+
+/// Tracks, for a set of signals, when each is next due to have its register
+/// refreshed. Indices correspond 1:1 with the `periods` passed to [`Scheduler::new`].
+pub(crate) struct Scheduler {
+    next_due: Vec<Instant>,
+    periods: Vec<Duration>,
+}
+
+impl Scheduler {
+    pub(crate) fn new(periods: Vec<Duration>) -> Self {
+        let now = Instant::now();
+        let next_due = vec![now; periods.len()];
+        Self { next_due, periods }
+    }
+
+    /// Returns the indices that are due at `now`, setting their `next_due`
+    /// to one period after `now` so they don't fire again until it elapses.
+    pub(crate) fn due(&mut self, now: Instant) -> Vec<usize> {
+        let mut due = Vec::new();
+        for i in 0..self.periods.len() {
+            if now >= self.next_due[i] {
+                due.push(i);
+                self.next_due[i] = now + self.periods[i];
+            }
+        }
+        due
+    }
+}
+
+/// Parses a period string like `"0ms"` (every tick), `"500ms"`, `"3s"`, or `"1m"`.
+pub(crate) fn parse_period(s: &str) -> Duration {
+    let s = s.trim();
+    if let Some(digits) = s.strip_suffix("ms") {
+        Duration::from_millis(digits.parse().unwrap_or_else(|_| panic!("invalid ms period \"{}\"", s)))
+    } else if let Some(digits) = s.strip_suffix('s') {
+        Duration::from_secs_f64(digits.parse().unwrap_or_else(|_| panic!("invalid s period \"{}\"", s)))
+    } else if let Some(digits) = s.strip_suffix('m') {
+        let minutes: f64 = digits.parse().unwrap_or_else(|_| panic!("invalid m period \"{}\"", s));
+        Duration::from_secs_f64(minutes * 60.0)
+    } else {
+        panic!("unrecognized period \"{}\" (expected e.g. \"500ms\", \"1s\", \"1m\")", s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_period_parses_milliseconds() {
+        assert_eq!(parse_period("0ms"), Duration::from_millis(0));
+        assert_eq!(parse_period("500ms"), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn parse_period_parses_seconds() {
+        assert_eq!(parse_period("3s"), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn parse_period_parses_minutes() {
+        assert_eq!(parse_period("1m"), Duration::from_secs(60));
+    }
+
+    #[test]
+    #[should_panic(expected = "unrecognized period")]
+    fn parse_period_rejects_unknown_unit() {
+        parse_period("5h");
+    }
+
+    #[test]
+    fn due_fires_on_the_first_call() {
+        let mut scheduler = Scheduler::new(vec![Duration::from_millis(100)]);
+        assert_eq!(scheduler.due(Instant::now()), vec![0]);
+    }
+
+    #[test]
+    fn due_does_not_fire_again_before_its_period_elapses() {
+        let mut scheduler = Scheduler::new(vec![Duration::from_millis(100)]);
+        let t0 = Instant::now();
+        assert_eq!(scheduler.due(t0), vec![0]);
+        assert!(scheduler.due(t0).is_empty());
+        assert!(scheduler.due(t0 + Duration::from_millis(50)).is_empty());
+        assert_eq!(scheduler.due(t0 + Duration::from_millis(100)), vec![0]);
+    }
+
+    #[test]
+    fn due_tracks_independent_signals_on_independent_periods() {
+        let mut scheduler = Scheduler::new(vec![Duration::from_millis(100), Duration::from_millis(200)]);
+        let t0 = Instant::now();
+        assert_eq!(scheduler.due(t0), vec![0, 1]);
+        assert_eq!(scheduler.due(t0 + Duration::from_millis(100)), vec![0]);
+        assert_eq!(scheduler.due(t0 + Duration::from_millis(200)), vec![0, 1]);
+    }
+}
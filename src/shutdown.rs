@@ -0,0 +1,30 @@
+//! Shutdown coordination: a broadcast "tripwire" fired once on SIGINT/SIGTERM
+//! so every subsystem can finish in-flight work and exit cleanly instead of
+//! being killed mid-request.
+
+use tokio::sync::broadcast;
+
+/// Spawns a task that waits for Ctrl+C (and SIGTERM on Unix) and fires `tripwire`.
+pub(crate) fn spawn_signal_listener(tripwire: broadcast::Sender<()>) {
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        println!("\nShutdown signal received, draining connections...");
+        let _ = tripwire.send(());
+    });
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate()).expect("failed to install SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = sigterm.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
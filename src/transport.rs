@@ -0,0 +1,220 @@
+//! Transport selection: the same `ModbusService`/`SharedState` can be served
+//! over TCP, RTU-over-serial, or RTU framing tunnelled through a TCP socket.
+
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::collections::HashSet;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_modbus::server::tcp::Server as TcpServer;
+#[cfg(feature = "rtu")]
+use tokio_modbus::server::rtu::Server as RtuServer;
+
+use crate::{ModbusConfig, ModbusService, SharedState};
+
+/// How the Modbus server is exposed to clients.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Transport {
+    #[default]
+    Tcp,
+    /// Modbus RTU over a local serial line; requires the `rtu` Cargo feature.
+    Rtu,
+    /// RTU framing (with CRC) tunnelled through a TCP socket instead of MBAP.
+    RtuOverTcp,
+}
+
+/// Serial line settings, required when `transport = "rtu"`.
+#[derive(Debug, Deserialize, Clone)]
+pub(crate) struct SerialConfig {
+    pub(crate) device: String,
+    #[serde(default = "SerialConfig::default_baud_rate")]
+    pub(crate) baud_rate: u32,
+    #[serde(default = "SerialConfig::default_slave_id")]
+    pub(crate) slave_id: u8,
+}
+
+impl SerialConfig {
+    fn default_baud_rate() -> u32 {
+        19200
+    }
+
+    fn default_slave_id() -> u8 {
+        1
+    }
+}
+
+/// Builds and runs the Modbus server for `config.transport`, reusing the same
+/// `ModbusService` and `SharedState` regardless of which transport is selected.
+pub(crate) async fn start_server(
+    config: &ModbusConfig,
+    state: Arc<Mutex<SharedState>>,
+    valid_addresses: Arc<HashSet<u16>>,
+    valid_coils: Arc<HashSet<u16>>,
+    tripwire: broadcast::Sender<()>,
+    shutting_down: Arc<AtomicBool>,
+    background_tasks: &mut Vec<JoinHandle<()>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match config.transport {
+        Transport::Tcp => serve_tcp(config.port, state, valid_addresses, valid_coils, tripwire, shutting_down).await,
+        Transport::Rtu => {
+            serve_rtu_serial(
+                config.serial.as_ref(),
+                state,
+                valid_addresses,
+                valid_coils,
+                tripwire,
+                shutting_down,
+                background_tasks,
+            )
+            .await
+        }
+        Transport::RtuOverTcp => {
+            serve_rtu_over_tcp(config.port, state, valid_addresses, valid_coils, tripwire, shutting_down).await
+        }
+    }
+}
+
+async fn serve_tcp(
+    port: u16,
+    state: Arc<Mutex<SharedState>>,
+    valid_addresses: Arc<HashSet<u16>>,
+    valid_coils: Arc<HashSet<u16>>,
+    tripwire: broadcast::Sender<()>,
+    shutting_down: Arc<AtomicBool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let socket_addr: SocketAddr = format!("0.0.0.0:{}", port).parse()?;
+    println!("Starting Modbus TCP server on {}", socket_addr);
+    let listener = tokio::net::TcpListener::bind(socket_addr).await?;
+    let server = TcpServer::new(listener);
+
+    let mut shutdown_rx = tripwire.subscribe();
+    tokio::select! {
+        result = server.serve(
+            &move |stream, _socket_addr| {  // stream first, then socket_addr
+                let state = state.clone();
+                let valid_addresses = valid_addresses.clone();
+                let valid_coils = valid_coils.clone();
+                let shutting_down = shutting_down.clone();
+                async move {
+                    let service = ModbusService { state, valid_addresses, valid_coils, shutting_down };
+                    Ok(Some((service, stream)))
+                }
+            },
+            &|err| {
+                eprintln!("Modbus connection error: {:?}", err);
+            },
+        ) => result.map_err(Into::into),
+        _ = shutdown_rx.recv() => {
+            println!("No longer accepting new Modbus connections.");
+            Ok(())
+        }
+    }
+}
+
+/// Unlike `serve_tcp`/`serve_rtu_over_tcp`, there's a single serial port and
+/// no per-connection spawn: `serve_forever` does its frame reads/writes
+/// in-line on that one future. Racing it directly against the tripwire in a
+/// `select!` would drop it (and any frame it's mid-read on) the instant
+/// shutdown fires, instead of letting the in-flight request finish the way
+/// #chunk0-4 requires. So it runs as its own background task instead, and
+/// this function only waits for the tripwire to report "shutting down".
+#[cfg(feature = "rtu")]
+async fn serve_rtu_serial(
+    serial: Option<&SerialConfig>,
+    state: Arc<Mutex<SharedState>>,
+    valid_addresses: Arc<HashSet<u16>>,
+    valid_coils: Arc<HashSet<u16>>,
+    tripwire: broadcast::Sender<()>,
+    shutting_down: Arc<AtomicBool>,
+    background_tasks: &mut Vec<JoinHandle<()>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use tokio_serial::SerialPortBuilderExt;
+
+    let serial = serial.ok_or("transport = \"rtu\" requires a [serial] section in the config")?;
+    println!(
+        "Starting Modbus RTU server on {} @ {} baud (slave {})",
+        serial.device, serial.baud_rate, serial.slave_id
+    );
+    let port = tokio_serial::new(&serial.device, serial.baud_rate).open_native_async()?;
+    let service = ModbusService { state, valid_addresses, valid_coils, shutting_down };
+
+    background_tasks.push(tokio::spawn(async move {
+        if let Err(e) = RtuServer::new(port).serve_forever(service).await {
+            eprintln!("RTU server error: {:?}", e);
+        }
+    }));
+
+    let mut shutdown_rx = tripwire.subscribe();
+    let _ = shutdown_rx.recv().await;
+    println!("RTU server shutting down.");
+    Ok(())
+}
+
+#[cfg(not(feature = "rtu"))]
+async fn serve_rtu_serial(
+    _serial: Option<&SerialConfig>,
+    _state: Arc<Mutex<SharedState>>,
+    _valid_addresses: Arc<HashSet<u16>>,
+    _valid_coils: Arc<HashSet<u16>>,
+    _tripwire: broadcast::Sender<()>,
+    _shutting_down: Arc<AtomicBool>,
+    _background_tasks: &mut Vec<JoinHandle<()>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("transport = \"rtu\" requires building with the \"rtu\" Cargo feature".into())
+}
+
+/// RTU framing tunnelled through a TCP socket instead of the usual MBAP
+/// header, for HIL testing against controllers that only speak RTU.
+#[cfg(feature = "rtu")]
+async fn serve_rtu_over_tcp(
+    port: u16,
+    state: Arc<Mutex<SharedState>>,
+    valid_addresses: Arc<HashSet<u16>>,
+    valid_coils: Arc<HashSet<u16>>,
+    tripwire: broadcast::Sender<()>,
+    shutting_down: Arc<AtomicBool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let socket_addr: SocketAddr = format!("0.0.0.0:{}", port).parse()?;
+    println!("Starting Modbus RTU-over-TCP server on {}", socket_addr);
+    let listener = tokio::net::TcpListener::bind(socket_addr).await?;
+
+    let mut shutdown_rx = tripwire.subscribe();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, peer) = accepted?;
+                println!("RTU-over-TCP client connected: {}", peer);
+                let service = ModbusService {
+                    state: state.clone(),
+                    valid_addresses: valid_addresses.clone(),
+                    valid_coils: valid_coils.clone(),
+                    shutting_down: shutting_down.clone(),
+                };
+                tokio::spawn(async move {
+                    if let Err(e) = RtuServer::new(stream).serve_forever(service).await {
+                        eprintln!("RTU-over-TCP connection error: {:?}", e);
+                    }
+                });
+            }
+            _ = shutdown_rx.recv() => {
+                println!("No longer accepting new RTU-over-TCP connections.");
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "rtu"))]
+async fn serve_rtu_over_tcp(
+    _port: u16,
+    _state: Arc<Mutex<SharedState>>,
+    _valid_addresses: Arc<HashSet<u16>>,
+    _valid_coils: Arc<HashSet<u16>>,
+    _tripwire: broadcast::Sender<()>,
+    _shutting_down: Arc<AtomicBool>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("transport = \"rtu-over-tcp\" requires building with the \"rtu\" Cargo feature".into())
+}